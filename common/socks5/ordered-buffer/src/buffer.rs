@@ -1,30 +1,176 @@
 use crate::message::Message;
+use std::collections::BTreeMap;
+use std::task::{Context, Poll, Waker};
+
+/// The outcome of a [`OrderedMessageBuffer::write`]. A rejected write carries
+/// the number of bytes that were dropped so the caller can apply backpressure
+/// upstream rather than silently losing data.
+#[derive(Debug, PartialEq, Eq)]
+pub enum WriteResult {
+    /// The message was stored in the buffer, or its index was already
+    /// buffered or consumed and it was ignored as a duplicate.
+    Written,
+    /// The message would have pushed the buffer past a configured limit and
+    /// was dropped; carries the number of bytes rejected.
+    Rejected(usize),
+}
+
+/// A snapshot of how full the buffer is, returned by
+/// [`OrderedMessageBuffer::limits`]. `capacity` and `free` are `None` when the
+/// buffer is unbounded.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BufferLimits {
+    /// Bytes currently buffered.
+    pub len: usize,
+    /// Maximum bytes the buffer will hold, or `None` if unbounded.
+    pub capacity: Option<usize>,
+    /// Bytes that can still be accepted, or `None` if unbounded.
+    pub free: Option<usize>,
+}
 
 /// Stores messages and emits them in order.
 ///
 /// Only contiguous messages with an index less than or equal to `next_index`
 /// will be returned - this avoids returning gaps while we wait for the buffer
 /// to fill up with the full sequence.
+///
+/// The buffer can be given a maximum number of buffered bytes and/or
+/// buffered fragments at construction. The fragment count covers every message
+/// held, whether or not it is already contiguous with `next_index`. Once full it applies a
+/// *reject-newest* policy: a write that would exceed a limit is dropped and the
+/// buffer keeps what it already holds. Evicting a buffered low-index fragment
+/// instead would open a gap that could never be filled from this buffer, so the
+/// newest arrival is the only safe thing to drop.
 #[derive(Debug)]
 pub struct OrderedMessageBuffer {
     next_index: u64,
-    messages: Vec<Message>,
+    messages: BTreeMap<u64, Message>,
+    buffered_bytes: usize,
+    max_bytes: Option<usize>,
+    max_fragments: Option<usize>,
+    waker: Option<Waker>,
 }
 
 impl OrderedMessageBuffer {
     pub fn new() -> OrderedMessageBuffer {
         OrderedMessageBuffer {
             next_index: 0,
-            messages: Vec::new(),
+            messages: BTreeMap::new(),
+            buffered_bytes: 0,
+            max_bytes: None,
+            max_fragments: None,
+            waker: None,
+        }
+    }
+
+    /// Creates a buffer bounded by a maximum number of buffered bytes and/or a
+    /// maximum number of buffered fragments (every message held until it is
+    /// read, contiguous or not). Passing `None` for either
+    /// leaves that dimension unbounded.
+    pub fn with_limits(
+        max_bytes: Option<usize>,
+        max_fragments: Option<usize>,
+    ) -> OrderedMessageBuffer {
+        OrderedMessageBuffer {
+            next_index: 0,
+            messages: BTreeMap::new(),
+            buffered_bytes: 0,
+            max_bytes,
+            max_fragments,
+            waker: None,
+        }
+    }
+
+    /// Returns a snapshot of the current fill level, configured byte capacity,
+    /// and remaining free space, so callers can apply backpressure before
+    /// memory blows up.
+    pub fn limits(&self) -> BufferLimits {
+        BufferLimits {
+            len: self.buffered_bytes,
+            capacity: self.max_bytes,
+            free: self.max_bytes.map(|max| max.saturating_sub(self.buffered_bytes)),
+        }
+    }
+
+    /// Writes a message to the buffer, keyed on its index. Insertion is
+    /// `O(log n)`, so filling the buffer with out-of-order chunks no longer
+    /// costs a quadratic re-sort, and reads can walk the already-ordered map
+    /// instead of cloning the whole buffer.
+    ///
+    /// Returns [`WriteResult::Rejected`] without storing anything when the
+    /// message would exceed a configured byte or fragment limit.
+    pub fn write(&mut self, message: Message) -> WriteResult {
+        let incoming = message.data.len();
+
+        // Ignore re-arrivals: an index we've already consumed or already hold
+        // would otherwise be inserted twice and corrupt the concatenation.
+        if message.index < self.next_index || self.messages.contains_key(&message.index) {
+            return WriteResult::Written;
+        }
+
+        if let Some(max_bytes) = self.max_bytes {
+            if self.buffered_bytes + incoming > max_bytes {
+                return WriteResult::Rejected(incoming);
+            }
+        }
+        if let Some(max_fragments) = self.max_fragments {
+            if self.messages.len() + 1 > max_fragments {
+                return WriteResult::Rejected(incoming);
+            }
+        }
+
+        // A message landing exactly at `next_index` extends the contiguous
+        // region, so a parked reader now has data to consume - wake it.
+        let extends_contiguous = message.index == self.next_index;
+        self.messages.insert(message.index, message);
+        self.buffered_bytes += incoming;
+        if extends_contiguous {
+            if let Some(waker) = self.waker.take() {
+                waker.wake();
+            }
+        }
+        WriteResult::Written
+    }
+
+    /// Async counterpart to [`read`](OrderedMessageBuffer::read). Returns
+    /// `Poll::Ready` with the next contiguous byte run when one is available,
+    /// otherwise parks the caller by storing its [`Waker`] and returns
+    /// `Poll::Pending`. The waker is woken by a later [`write`] that fills the
+    /// gap at `next_index`, so callers no longer have to busy-poll.
+    pub fn poll_read(&mut self, cx: &mut Context<'_>) -> Poll<Vec<u8>> {
+        match self.read() {
+            Some(data) => Poll::Ready(data),
+            None => {
+                self.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
         }
     }
 
-    /// Writes a message to the buffer. messages are sort on insertion, so
-    /// that later on multiple reads for incomplete sequences don't result in
-    /// useless sort work.
-    pub fn write(&mut self, message: Message) {
-        self.messages.push(message);
-        OrderedMessageBuffer::insertion_sort(&mut self.messages);
+    /// Returns the contiguous runs of indices that are missing between
+    /// `next_index` and the highest index currently buffered, so a receiver can
+    /// turn them into selective retransmission requests. For example, with
+    /// `next_index == 3` and indices `{5, 9}` buffered this returns
+    /// `[3..5, 6..9]`. Returns an empty vector when the buffer has no gaps (or
+    /// is empty).
+    pub fn missing_ranges(&self) -> Vec<std::ops::Range<u64>> {
+        let highest = match self.messages.keys().next_back() {
+            Some(&highest) => highest,
+            None => return Vec::new(),
+        };
+
+        let mut ranges = Vec::new();
+        let mut gap_start = None;
+        for index in self.next_index..=highest {
+            if self.messages.contains_key(&index) {
+                if let Some(start) = gap_start.take() {
+                    ranges.push(start..index);
+                }
+            } else if gap_start.is_none() {
+                gap_start = Some(index);
+            }
+        }
+        ranges
     }
 
     /// Returns `Option<Vec<u8>>` where it's `Some(bytes)` if there is gapless
@@ -35,47 +181,20 @@ impl OrderedMessageBuffer {
     /// at which point 3, 4, and any further contiguous messages which have arrived
     /// will be returned.
     pub fn read(&mut self) -> Option<Vec<u8>> {
-        if self.messages.is_empty() || self.messages.first().unwrap().index > self.next_index {
+        if !self.messages.contains_key(&self.next_index) {
             return None;
-        } else {
-            let index = self.next_index.clone() + 1;
-            let contiguous_messages: Vec<Message> = self
-                .messages
-                .iter()
-                .filter(|message| message.index <= index)
-                .cloned()
-                .collect();
-
-            // get rid of all messages we're about to send out of the buffer
-            self.messages.retain(|message| message.index > index);
-
-            // advance the index because we've read stuff up to a new high water mark
-            let high_water = index + contiguous_messages.len() as u64 - 1;
-            self.next_index = high_water;
-
-            // dig out the bytes from inside the struct
-            let data = contiguous_messages
-                .iter()
-                .flat_map(|message| message.data.clone())
-                .collect();
-
-            Some(data)
         }
-    }
 
-    fn insertion_sort<T>(values: &mut [T])
-    where
-        T: Ord,
-    {
-        for i in 0..values.len() {
-            for j in (0..i).rev() {
-                if values[j] >= values[j + 1] {
-                    values.swap(j, j + 1);
-                } else {
-                    break;
-                }
-            }
+        // Walk contiguous messages starting at `next_index`, removing and
+        // concatenating each one until we hit the first gap.
+        let mut data = Vec::new();
+        while let Some(message) = self.messages.remove(&self.next_index) {
+            self.buffered_bytes -= message.data.len();
+            data.extend(message.data);
+            self.next_index += 1;
         }
+
+        Some(data)
     }
 }
 
@@ -237,5 +356,157 @@ mod test_chunking_and_reassembling {
                 assert_eq!(None, buffer.read());
             }
         }
+
+        mod when_the_buffer_has_a_capacity_limit {
+            use super::*;
+            use crate::message::Message;
+
+            #[test]
+            fn a_write_that_fits_is_accepted_and_counted() {
+                let mut buffer = OrderedMessageBuffer::with_limits(Some(8), None);
+                assert_eq!(
+                    WriteResult::Written,
+                    buffer.write(Message {
+                        data: vec![1, 2, 3, 4],
+                        index: 0,
+                    })
+                );
+                let limits = buffer.limits();
+                assert_eq!(4, limits.len);
+                assert_eq!(Some(8), limits.capacity);
+                assert_eq!(Some(4), limits.free);
+            }
+
+            #[test]
+            fn a_write_that_would_overflow_is_rejected_without_storing() {
+                let mut buffer = OrderedMessageBuffer::with_limits(Some(4), None);
+                buffer.write(Message {
+                    data: vec![1, 2, 3, 4],
+                    index: 0,
+                });
+                assert_eq!(
+                    WriteResult::Rejected(2),
+                    buffer.write(Message {
+                        data: vec![5, 6],
+                        index: 1,
+                    })
+                );
+                assert_eq!(4, buffer.limits().len);
+            }
+
+            #[test]
+            fn reading_frees_capacity_again() {
+                let mut buffer = OrderedMessageBuffer::with_limits(Some(4), None);
+                buffer.write(Message {
+                    data: vec![1, 2, 3, 4],
+                    index: 0,
+                });
+                buffer.read();
+                assert_eq!(0, buffer.limits().len);
+                assert_eq!(Some(4), buffer.limits().free);
+            }
+
+            #[test]
+            fn the_fragment_count_can_be_bounded_independently() {
+                let mut buffer = OrderedMessageBuffer::with_limits(None, Some(1));
+                // Index 0 never arrives, so index 1 is held out of order and
+                // uses up the cap of one fragment; index 2 is then rejected.
+                buffer.write(Message {
+                    data: vec![1],
+                    index: 1,
+                });
+                assert_eq!(
+                    WriteResult::Rejected(1),
+                    buffer.write(Message {
+                        data: vec![2],
+                        index: 2,
+                    })
+                );
+            }
+        }
+
+        mod reporting_and_deduplicating_gaps {
+            use super::*;
+            use crate::message::Message;
+
+            fn message(index: u64) -> Message {
+                Message {
+                    data: vec![index as u8],
+                    index,
+                }
+            }
+
+            #[test]
+            fn missing_ranges_lists_the_absent_runs() {
+                let mut buffer = OrderedMessageBuffer::new();
+                buffer.write(message(0));
+                buffer.write(message(1));
+                buffer.write(message(4));
+                buffer.write(message(9));
+
+                assert_eq!(vec![2..4, 5..9], buffer.missing_ranges());
+            }
+
+            #[test]
+            fn missing_ranges_is_empty_with_no_gaps() {
+                let mut buffer = OrderedMessageBuffer::new();
+                buffer.write(message(0));
+                buffer.write(message(1));
+                assert!(buffer.missing_ranges().is_empty());
+            }
+
+            #[test]
+            fn a_duplicate_index_is_ignored() {
+                let mut buffer = OrderedMessageBuffer::new();
+                buffer.write(Message {
+                    data: vec![1, 2, 3, 4],
+                    index: 0,
+                });
+                // A retransmission of the same index must not stack up bytes.
+                buffer.write(Message {
+                    data: vec![9, 9, 9, 9],
+                    index: 0,
+                });
+                assert_eq!(4, buffer.limits().len);
+                assert_eq!(vec![1, 2, 3, 4], buffer.read().unwrap());
+            }
+
+            #[test]
+            fn an_already_consumed_index_is_ignored() {
+                let mut buffer = OrderedMessageBuffer::new();
+                buffer.write(message(0));
+                buffer.read();
+                // Index 0 has been emitted; re-delivering it must not corrupt
+                // the next read.
+                buffer.write(message(0));
+                assert_eq!(0, buffer.limits().len);
+                assert_eq!(None, buffer.read());
+            }
+        }
+
+        mod polling_for_readiness {
+            use super::*;
+            use crate::message::Message;
+            use futures::task::noop_waker;
+
+            #[test]
+            fn poll_read_is_pending_until_the_gap_is_filled() {
+                let mut buffer = OrderedMessageBuffer::new();
+                let waker = noop_waker();
+                let mut cx = Context::from_waker(&waker);
+
+                buffer.write(Message {
+                    data: vec![3, 4],
+                    index: 1,
+                });
+                assert_eq!(Poll::Pending, buffer.poll_read(&mut cx));
+
+                buffer.write(Message {
+                    data: vec![1, 2],
+                    index: 0,
+                });
+                assert_eq!(Poll::Ready(vec![1, 2, 3, 4]), buffer.poll_read(&mut cx));
+            }
+        }
     }
 }
@@ -0,0 +1,162 @@
+use crate::buffer::{OrderedMessageBuffer, WriteResult};
+use crate::message::Message;
+use std::collections::BTreeMap;
+
+/// Identifies one logical ordered stream within a [`StreamMultiplexer`].
+pub type StreamId = u64;
+
+/// The scheduling priority of a logical stream. Lower values are served first,
+/// so `HIGH` (0) outranks `NORMAL` which outranks `BACKGROUND`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestPriority(pub u8);
+
+impl RequestPriority {
+    /// Latency-sensitive interactive traffic.
+    pub const HIGH: RequestPriority = RequestPriority(0);
+    /// The default for streams registered without an explicit priority.
+    pub const NORMAL: RequestPriority = RequestPriority(128);
+    /// Bulk transfers that must never starve higher-priority streams.
+    pub const BACKGROUND: RequestPriority = RequestPriority(255);
+}
+
+impl Default for RequestPriority {
+    fn default() -> RequestPriority {
+        RequestPriority::NORMAL
+    }
+}
+
+#[derive(Debug)]
+struct LogicalStream {
+    priority: RequestPriority,
+    buffer: OrderedMessageBuffer,
+}
+
+/// Multiplexes several [`OrderedMessageBuffer`]s - one per logical stream -
+/// behind a single reassembly point, scheduling their output by priority.
+///
+/// [`drain`](StreamMultiplexer::drain) serves the highest-priority streams
+/// first and round-robins among streams of equal priority, so a large
+/// background transfer sharing the mixnet can never starve a latency-sensitive
+/// interactive stream even though both flow through the same machinery.
+#[derive(Debug, Default)]
+pub struct StreamMultiplexer {
+    streams: BTreeMap<StreamId, LogicalStream>,
+    /// Rotates the starting point within each equal-priority group so that no
+    /// single stream of a given priority is always served first.
+    rotation: usize,
+}
+
+impl StreamMultiplexer {
+    pub fn new() -> StreamMultiplexer {
+        StreamMultiplexer {
+            streams: BTreeMap::new(),
+            rotation: 0,
+        }
+    }
+
+    /// Registers a stream with an explicit priority, replacing any existing
+    /// registration for that id. Streams that are never registered default to
+    /// [`RequestPriority::NORMAL`] on first write.
+    pub fn register(&mut self, stream_id: StreamId, priority: RequestPriority) {
+        self.streams.insert(
+            stream_id,
+            LogicalStream {
+                priority,
+                buffer: OrderedMessageBuffer::new(),
+            },
+        );
+    }
+
+    /// Routes a message to its stream's buffer, creating the stream with the
+    /// default priority if it has not been registered yet.
+    pub fn write(&mut self, stream_id: StreamId, message: Message) -> WriteResult {
+        self.streams
+            .entry(stream_id)
+            .or_insert_with(|| LogicalStream {
+                priority: RequestPriority::default(),
+                buffer: OrderedMessageBuffer::new(),
+            })
+            .buffer
+            .write(message)
+    }
+
+    /// Emits all currently-ready contiguous byte runs, highest priority first
+    /// and round-robining among streams of equal priority. Only streams that
+    /// have data to hand out appear in the result.
+    pub fn drain(&mut self) -> Vec<(StreamId, Vec<u8>)> {
+        // Group stream ids by priority. Each group's ids are naturally sorted
+        // because we iterate the `BTreeMap` in id order.
+        let mut by_priority: BTreeMap<RequestPriority, Vec<StreamId>> = BTreeMap::new();
+        for (&stream_id, stream) in &self.streams {
+            by_priority.entry(stream.priority).or_default().push(stream_id);
+        }
+
+        let mut drained = Vec::new();
+        for ids in by_priority.values() {
+            // Rotate the serving order within this priority band for fairness.
+            let offset = self.rotation % ids.len();
+            for i in 0..ids.len() {
+                let stream_id = ids[(offset + i) % ids.len()];
+                if let Some(data) = self.streams.get_mut(&stream_id).unwrap().buffer.read() {
+                    drained.push((stream_id, data));
+                }
+            }
+        }
+
+        self.rotation = self.rotation.wrapping_add(1);
+        drained
+    }
+}
+
+#[cfg(test)]
+mod multiplexing_priority_streams {
+    use super::*;
+
+    fn message(index: u64, byte: u8) -> Message {
+        Message {
+            data: vec![byte],
+            index,
+        }
+    }
+
+    #[test]
+    fn higher_priority_streams_are_served_first() {
+        let mut mux = StreamMultiplexer::new();
+        mux.register(1, RequestPriority::BACKGROUND);
+        mux.register(2, RequestPriority::HIGH);
+
+        mux.write(1, message(0, 0xbb));
+        mux.write(2, message(0, 0xaa));
+
+        let drained = mux.drain();
+        assert_eq!(
+            vec![(2, vec![0xaa]), (1, vec![0xbb])],
+            drained,
+            "the HIGH stream should come before the BACKGROUND one"
+        );
+    }
+
+    #[test]
+    fn equal_priority_streams_are_round_robined() {
+        let mut mux = StreamMultiplexer::new();
+        mux.register(1, RequestPriority::NORMAL);
+        mux.register(2, RequestPriority::NORMAL);
+
+        // First drain serves 1 before 2.
+        mux.write(1, message(0, 1));
+        mux.write(2, message(0, 2));
+        assert_eq!(vec![(1, vec![1]), (2, vec![2])], mux.drain());
+
+        // Second drain rotates, serving 2 before 1.
+        mux.write(1, message(1, 1));
+        mux.write(2, message(1, 2));
+        assert_eq!(vec![(2, vec![2]), (1, vec![1])], mux.drain());
+    }
+
+    #[test]
+    fn streams_with_gaps_are_skipped() {
+        let mut mux = StreamMultiplexer::new();
+        mux.write(1, message(1, 9)); // gap at index 0
+        assert!(mux.drain().is_empty());
+    }
+}
@@ -0,0 +1,159 @@
+/// Reassembles a contiguous byte stream from possibly overlapping or
+/// duplicated segments, the way a QUIC stream receiver does.
+///
+/// Unlike [`OrderedMessageBuffer`](crate::buffer::OrderedMessageBuffer), which
+/// keys whole messages on a dense `index`, this buffer keys arbitrary byte
+/// fragments on their start `offset`. That lets it cope with chunks that have
+/// been retransmitted or re-split at different boundaries: overlapping segments
+/// are trimmed against what is already present, fully-contained duplicates are
+/// dropped, and adjacent fragments are coalesced so the map never holds two
+/// ranges that touch or overlap.
+///
+/// Fragments are stored in a `BTreeMap<u64, Vec<u8>>` keyed on their start
+/// offset. `contiguous_len` tracks the offset of the first gap in the stream
+/// and `read_cursor` tracks how far the reader has already consumed, so bytes
+/// are never returned twice.
+#[derive(Debug, Default)]
+pub struct ByteReassemblyBuffer {
+    fragments: std::collections::BTreeMap<u64, Vec<u8>>,
+    contiguous_len: u64,
+    read_cursor: u64,
+}
+
+impl ByteReassemblyBuffer {
+    pub fn new() -> ByteReassemblyBuffer {
+        ByteReassemblyBuffer {
+            fragments: std::collections::BTreeMap::new(),
+            contiguous_len: 0,
+            read_cursor: 0,
+        }
+    }
+
+    /// Writes a fragment carrying the bytes that begin at `offset`.
+    ///
+    /// The incoming range is `[offset, offset + data.len())`. Any portion that
+    /// lies below the already-consumed read cursor is discarded, any portion
+    /// that overlaps bytes we already hold is trimmed (the bytes already in the
+    /// buffer win, so a duplicate retransmission never overwrites good data),
+    /// and the remainder is merged with any adjacent or overlapping fragments
+    /// into a single entry.
+    pub fn write(&mut self, offset: u64, data: Vec<u8>) {
+        let end = offset + data.len() as u64;
+
+        // Drop anything that lies entirely below the read cursor - those bytes
+        // have already been emitted and must never be returned again.
+        if end <= self.read_cursor {
+            return;
+        }
+        let (start, data) = if offset < self.read_cursor {
+            let trim = (self.read_cursor - offset) as usize;
+            (self.read_cursor, data[trim..].to_vec())
+        } else {
+            (offset, data)
+        };
+
+        // Find every fragment that overlaps or touches `[start, end)` so we can
+        // fold them all into one coalesced entry.
+        let mut merged_start = start;
+        let mut merged_end = end;
+        let mut overlapping = Vec::new();
+        for (&frag_start, frag) in self.fragments.range(..=end) {
+            let frag_end = frag_start + frag.len() as u64;
+            if frag_end >= start {
+                overlapping.push(frag_start);
+                merged_start = merged_start.min(frag_start);
+                merged_end = merged_end.max(frag_end);
+            }
+        }
+
+        // Lay the new bytes down first, then overlay the existing fragments on
+        // top so that bytes we already held take precedence on any overlap.
+        let mut merged = vec![0u8; (merged_end - merged_start) as usize];
+        let data_at = (start - merged_start) as usize;
+        merged[data_at..data_at + data.len()].copy_from_slice(&data);
+        for frag_start in overlapping {
+            let frag = self.fragments.remove(&frag_start).unwrap();
+            let at = (frag_start - merged_start) as usize;
+            merged[at..at + frag.len()].copy_from_slice(&frag);
+        }
+        self.fragments.insert(merged_start, merged);
+
+        self.recompute_contiguous_len();
+    }
+
+    /// Returns the bytes from the last read position up to the first gap, or
+    /// `None` if no new contiguous bytes are available. Consumed bytes are
+    /// removed from the buffer and never returned again.
+    pub fn read(&mut self) -> Option<Vec<u8>> {
+        if self.contiguous_len <= self.read_cursor {
+            return None;
+        }
+        let data = self.fragments.remove(&self.read_cursor)?;
+        self.read_cursor = self.contiguous_len;
+        Some(data)
+    }
+
+    /// Recomputes `contiguous_len` as the end of the fragment anchored at the
+    /// read cursor, which is the offset of the first gap in the stream.
+    fn recompute_contiguous_len(&mut self) {
+        self.contiguous_len = match self.fragments.get(&self.read_cursor) {
+            Some(frag) => self.read_cursor + frag.len() as u64,
+            None => self.read_cursor,
+        };
+    }
+}
+
+#[cfg(test)]
+mod reassembling_a_byte_stream {
+    use super::*;
+
+    #[test]
+    fn contiguous_writes_read_back_in_order() {
+        let mut buffer = ByteReassemblyBuffer::new();
+        buffer.write(0, vec![1, 2, 3, 4]);
+        buffer.write(4, vec![5, 6, 7, 8]);
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7, 8], buffer.read().unwrap());
+        assert_eq!(None, buffer.read());
+    }
+
+    #[test]
+    fn a_gap_stops_the_read_until_it_is_filled() {
+        let mut buffer = ByteReassemblyBuffer::new();
+        buffer.write(0, vec![1, 2]);
+        buffer.write(4, vec![5, 6]);
+        assert_eq!(vec![1, 2], buffer.read().unwrap());
+        assert_eq!(None, buffer.read());
+
+        buffer.write(2, vec![3, 4]);
+        assert_eq!(vec![3, 4, 5, 6], buffer.read().unwrap());
+    }
+
+    #[test]
+    fn a_fully_contained_duplicate_is_dropped() {
+        let mut buffer = ByteReassemblyBuffer::new();
+        buffer.write(0, vec![1, 2, 3, 4]);
+        // Retransmission of bytes we already hold - must not be returned twice.
+        buffer.write(1, vec![9, 9]);
+        assert_eq!(vec![1, 2, 3, 4], buffer.read().unwrap());
+    }
+
+    #[test]
+    fn a_partial_overlap_is_trimmed_not_rejected() {
+        let mut buffer = ByteReassemblyBuffer::new();
+        buffer.write(0, vec![1, 2, 3, 4]);
+        // Overlaps the last two bytes and extends past them; existing bytes win.
+        buffer.write(2, vec![9, 9, 5, 6]);
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], buffer.read().unwrap());
+    }
+
+    #[test]
+    fn bytes_below_the_read_cursor_are_never_returned_twice() {
+        let mut buffer = ByteReassemblyBuffer::new();
+        buffer.write(0, vec![1, 2, 3, 4]);
+        assert_eq!(vec![1, 2, 3, 4], buffer.read().unwrap());
+
+        // Re-delivery of already-emitted bytes, plus one fresh byte.
+        buffer.write(0, vec![1, 2, 3, 4, 5]);
+        assert_eq!(vec![5], buffer.read().unwrap());
+    }
+}
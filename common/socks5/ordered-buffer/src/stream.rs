@@ -0,0 +1,68 @@
+use crate::buffer::{OrderedMessageBuffer, WriteResult};
+use crate::message::Message;
+use futures::Stream;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// A [`futures::Stream`] wrapper around an [`OrderedMessageBuffer`].
+///
+/// Feed messages in with [`write`](OrderedMessageStream::write) and pull
+/// reassembled, ordered byte runs out by polling the stream. Each item is a
+/// contiguous run of bytes; the stream parks (returns `Poll::Pending`) while a
+/// gap at `next_index` is outstanding and resumes as soon as a later write
+/// fills it, so downstream consumers can be piped directly off it without
+/// spin-looping on `read`.
+#[derive(Debug)]
+pub struct OrderedMessageStream {
+    buffer: OrderedMessageBuffer,
+}
+
+impl OrderedMessageStream {
+    pub fn new(buffer: OrderedMessageBuffer) -> OrderedMessageStream {
+        OrderedMessageStream { buffer }
+    }
+
+    /// Writes a message into the underlying buffer, waking the stream if the
+    /// message extends the contiguous region.
+    pub fn write(&mut self, message: Message) -> WriteResult {
+        self.buffer.write(message)
+    }
+}
+
+impl Stream for OrderedMessageStream {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Vec<u8>>> {
+        self.get_mut().buffer.poll_read(cx).map(Some)
+    }
+}
+
+#[cfg(test)]
+mod streaming_ordered_bytes {
+    use super::*;
+    use futures::task::noop_waker;
+
+    #[test]
+    fn yields_contiguous_runs_and_parks_on_gaps() {
+        let mut stream = OrderedMessageStream::new(OrderedMessageBuffer::new());
+        let waker = noop_waker();
+        let mut cx = Context::from_waker(&waker);
+
+        // Nothing contiguous yet - the stream parks.
+        stream.write(Message {
+            data: vec![3, 4],
+            index: 1,
+        });
+        assert_eq!(Poll::Pending, Pin::new(&mut stream).poll_next(&mut cx));
+
+        // Filling the gap makes the whole contiguous run ready.
+        stream.write(Message {
+            data: vec![1, 2],
+            index: 0,
+        });
+        assert_eq!(
+            Poll::Ready(Some(vec![1, 2, 3, 4])),
+            Pin::new(&mut stream).poll_next(&mut cx)
+        );
+    }
+}